@@ -1,30 +1,101 @@
 // Solana Smart Lottery
 use solana_program::{
-    program_error::ProgramError,
+    account_info::AccountInfo,
+    clock::Clock,
+    decode_error::DecodeError,
+    msg,
+    program_error::{PrintProgramError, ProgramError},
     program_pack::{IsInitialized, Pack, Sealed},
     pubkey::Pubkey,
+    slot_hashes::SlotHashes,
+    sysvar::Sysvar,
 };
 use std::collections::{HashMap, HashSet};
 use sha2::{Sha256, Digest};
 use std::convert::TryInto;
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+use thiserror::Error;
 
-// Custom Error Types
+// Deserialize the Clock sysvar account the same way the rest of the
+// ecosystem reads sysvars, so `unix_timestamp`/`slot` come from on-chain
+// consensus time instead of the BPF host's wall clock.
+pub fn clock_from_sysvar_account(clock_sysvar_account: &AccountInfo) -> Result<Clock, ProgramError> {
+    Clock::from_account_info(clock_sysvar_account)
+}
+
+// Custom Error Types. Deriving `FromPrimitive` lets a `u32` error code
+// round-trip back to the variant (and its message below), the same way
+// the stake/vote programs decode their custom program errors.
+#[derive(Clone, Debug, Eq, PartialEq, Error, FromPrimitive)]
 pub enum LotteryError {
+    #[error("Caller is rate limited")]
     RateLimited = 0,
+    #[error("Caller is not authorized")]
     Unauthorized,
+    #[error("Lottery is already initialized")]
     AlreadyInitialized,
+    #[error("No tickets remaining")]
     OutOfTickets,
+    #[error("Invalid input")]
     InvalidInput,
+    #[error("Invalid admin address")]
     InvalidAdmin,
+    #[error("Invalid payout structure")]
     InvalidPayoutStructure,
+    #[error("Time-lock already set")]
     TimeLockAlreadySet,
+    #[error("Invalid draw time")]
     InvalidDrawTime,
+    #[error("Invalid random seed")]
     InvalidRandomSeed,
+    #[error("No tickets sold")]
     NoTicketsSold,
+    #[error("Invalid wallet address")]
     InvalidWalletAddress,
+    #[error("Invalid deposit")]
     InvalidDeposit,
+    #[error("Duplicate ticket purchase")]
     DuplicateTicketPurchase,
-    AclViolation,   
+    #[error("ACL violation")]
+    AclViolation,
+    #[error("Invalid lifecycle phase for this operation")]
+    InvalidPhase,
+    #[error("Refunds are outstanding")]
+    RefundsOutstanding,
+    #[error("Owner fee has not been collected for this round")]
+    FeeNotCollected,
+}
+
+impl From<LotteryError> for ProgramError {
+    fn from(e: LotteryError) -> Self {
+        ProgramError::Custom(e as u32)
+    }
+}
+
+impl<T> DecodeError<T> for LotteryError {
+    fn type_of() -> &'static str {
+        "LotteryError"
+    }
+}
+
+impl PrintProgramError for LotteryError {
+    fn print<E>(&self)
+    where
+        E: 'static + std::error::Error + DecodeError<E> + PrintProgramError + FromPrimitive,
+    {
+        msg!("{}", &self.to_string());
+    }
+}
+
+// Lifecycle Phase
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Setup,
+    SalesActive,
+    DrawPending,
+    Complete,
+    Refunding,
 }
 
 // Logging Level
@@ -72,52 +143,100 @@ pub mod logging {
     }
 }
 
-// Payout Struct
+// A single prize tier: `share_bps` basis points of `contract_balance`,
+// split evenly across `winner_count` winners.
+pub struct PayoutTier {
+    pub share_bps: u128,
+    pub winner_count: u64,
+}
+
+// Payout Struct: an ordered list of tiers (e.g. grand prize, then minor
+// prizes), generalizing the old fixed minor/grand split.
 pub struct PayoutStructure {
-    minor: u128,
-    grand: u128,
-    // Add more as needed
+    pub tiers: Vec<PayoutTier>,
+}
+
+// Basis points reserved for the owner's fee; tiers may not eat into it.
+pub const OWNER_FEE_BPS: u128 = 2;
+
+// Returns the (bitmask, byte index) for ticket sequence number `seq`,
+// mirroring the fair-launch program's `get_mask_and_index_for_seq`.
+fn get_mask_and_index_for_seq(seq: u64) -> (u8, usize) {
+    let index = (seq / 8) as usize;
+    let mask = 1u8 << (seq % 8);
+    (mask, index)
 }
 
 // Lottery Struct
 pub struct Lottery {
-    ticket_data: HashMap<u64, Pubkey>,  // <- Changed to HashMap<u64, Pubkey>
+    // Participants indexed by ticket sequence number, paired with a packed
+    // bitvector marking which sequence numbers have been sold. Replaces the
+    // old HashMap<u64, Pubkey>, which couldn't round-trip through account
+    // data and made ticket lookup anything but O(1).
+    participants: Vec<Pubkey>,
+    sold_mask: Vec<u8>,
     total_tickets: u64,
     sold_tickets: u64,
     contract_balance: u128,
     ticket_price: u128,
     draw_time: u64,
     random_seed: u64,
+    // sha256(secret) posted by the admin during the commit phase, verified
+    // against the revealed secret at draw time.
+    commitment: [u8; 32],
     payout_structure: PayoutStructure,
     admin_address: Pubkey,
     rate_limit_map: HashMap<Pubkey, u64>,
     acl: HashSet<Pubkey>,
+    phase: Phase,
+    // Total lamports deposited per participant across their ticket
+    // purchases, used to compute refunds (cancellation or overpayment).
+    deposits: HashMap<Pubkey, u128>,
+    // Incremented by `roll_over`; namespaces ticket-id/randomness hashing
+    // so rounds never collide with each other.
+    round: u64,
+    // Set by `collect_owner_fee`; `roll_over` refuses to reset the round
+    // until this is true, so the owner's cut can't be skipped by rolling
+    // over straight after `transfer_winnings`.
+    fee_collected: bool,
 }
 
 impl Lottery {
     // Initialize a new Lottery struct
     pub fn new() -> Lottery {
         Lottery {
-            ticket_data: HashMap::new(),
+            participants: Vec::new(),
+            sold_mask: Vec::new(),
             total_tickets: 0,
             sold_tickets: 0,
             contract_balance: 0,
             ticket_price: 0,
             draw_time: 0,
             random_seed: 0,
-            payout_structure: PayoutStructure {
-                minor: 0,
-                grand: 0,
-            },
+            commitment: [0u8; 32],
+            payout_structure: PayoutStructure { tiers: Vec::new() },
             admin_address: Pubkey::default(),
             rate_limit_map: HashMap::new(),
             acl: HashSet::new(),
-        }   
+            phase: Phase::Setup,
+            deposits: HashMap::new(),
+            round: 0,
+            fee_collected: false,
+        }
+    }
+
+    // Asserts the lottery is in `expected` phase before a mutating method
+    // is allowed to proceed.
+    fn assert_phase(&self, expected: Phase) -> Result<(), ProgramError> {
+        if self.phase != expected {
+            return Err(LotteryError::InvalidPhase.into());
+        }
+        Ok(())
     }
     
     pub fn validate_admin(&self, admin_address: Pubkey) -> Result<(), ProgramError> {
         if admin_address != self.admin_address && self.admin_address != Pubkey::default() {
-            return Err(ProgramError::Custom(LotteryError::InvalidAdmin as u32));
+            return Err(LotteryError::InvalidAdmin.into());
         }
         Ok(())
     }
@@ -129,36 +248,43 @@ impl Lottery {
         payout_structure: PayoutStructure,
         admin_address: Pubkey,
     ) -> Result<(), ProgramError> {
+        self.assert_phase(Phase::Setup)?;
         if self.total_tickets != 0 {
-            return Err(ProgramError::Custom(LotteryError::AlreadyInitialized as u32));
+            return Err(LotteryError::AlreadyInitialized.into());
         }
         if total_tickets == 0 || ticket_price == 0 || admin_address == Pubkey::default() {
-            return Err(ProgramError::Custom(LotteryError::InvalidInput as u32));
+            return Err(LotteryError::InvalidInput.into());
         }
-        let total_percentage: u128 = payout_structure.minor + payout_structure.grand;
-        if total_percentage > 10000 {
-            return Err(ProgramError::Custom(LotteryError::InvalidPayoutStructure as u32));
+        if payout_structure.tiers.len() > u8::MAX as usize {
+            return Err(LotteryError::InvalidPayoutStructure.into());
+        }
+        let total_share_bps: u128 = payout_structure.tiers.iter().map(|tier| tier.share_bps).sum();
+        if total_share_bps + OWNER_FEE_BPS > 10000 {
+            return Err(LotteryError::InvalidPayoutStructure.into());
         }
         self.total_tickets = total_tickets;
         self.ticket_price = ticket_price;
         self.payout_structure = payout_structure;
         self.admin_address = admin_address;
+        self.participants = vec![Pubkey::default(); total_tickets as usize];
+        self.sold_mask = vec![0u8; Self::mask_len(total_tickets)];
+        self.phase = Phase::SalesActive;
         Ok(())
     }
     // Data Validation
     pub fn validate_data(&self, user_deposit: u128) -> Result<(), ProgramError> {
         if user_deposit < self.ticket_price {
-            return Err(ProgramError::Custom(LotteryError::InvalidDeposit as u32));
+            return Err(LotteryError::InvalidDeposit.into());
         }
         Ok(())
     }
 
     // New method to wrap several existing methods
-    pub fn new_ticket(&mut self, user_wallet_address: Pubkey, user_deposit: u128) -> Result<(), ProgramError> {
+    pub fn new_ticket(&mut self, clock: &Clock, user_wallet_address: Pubkey, user_deposit: u128) -> Result<(), ProgramError> {
         self.validate_data(user_deposit)?;
         self.check_availability()?;
         self.log_new_ticket(user_wallet_address);
-        self.allocate_tickets_with_u128(user_wallet_address, user_deposit)
+        self.allocate_tickets_with_u128(clock, user_wallet_address, user_deposit)
     }
 
     // New Modular Function for logging
@@ -169,120 +295,282 @@ impl Lottery {
     // Check Availability of Tickets
     pub fn check_availability(&self) -> Result<(), ProgramError> {
         if self.sold_tickets >= self.total_tickets {
-            return Err(ProgramError::Custom(LotteryError::OutOfTickets as u32));
+            return Err(LotteryError::OutOfTickets.into());
         }
         Ok(())
     }
 
-    // Accept User Deposit
-    pub fn allocate_tickets_with_u128(&mut self, user_wallet_address: Pubkey, user_deposit: u128) -> Result<(), ProgramError> { 
+    // Accept User Deposit and Issue Tickets. Delegates the actual ticket
+    // issuance to `allocate_tickets_with_f64` so a buyer going through
+    // `new_ticket` ends up with real tickets, not just a balance bump.
+    pub fn allocate_tickets_with_u128(&mut self, clock: &Clock, user_wallet_address: Pubkey, user_deposit: u128) -> Result<(), ProgramError> {
+        self.assert_phase(Phase::SalesActive)?;
         self.validate_data(user_deposit)?;
         self.contract_balance += user_deposit;
-        Ok(())
+        self.allocate_tickets_with_f64(clock, user_wallet_address, user_deposit)
     }
 
     // Allocate Tickets to User
-    pub fn allocate_tickets_with_f64(&mut self, user_wallet_address: Pubkey, user_deposit: u128) -> Result<(), ProgramError> {
+    pub fn allocate_tickets_with_f64(&mut self, clock: &Clock, user_wallet_address: Pubkey, user_deposit: u128) -> Result<(), ProgramError> {
+        self.assert_phase(Phase::SalesActive)?;
         if user_wallet_address == Pubkey::default() {
-            return Err(ProgramError::Custom(LotteryError::InvalidWalletAddress as u32));
+            return Err(LotteryError::InvalidWalletAddress.into());
         }
+        *self.deposits.entry(user_wallet_address).or_insert(0) += user_deposit;
         let num_tickets = user_deposit / self.ticket_price;
         let total_tickets_sold = self.sold_tickets as u128 + num_tickets;
         if total_tickets_sold > self.total_tickets as u128 {
-            return Err(ProgramError::Custom(LotteryError::OutOfTickets as u32));
+            return Err(LotteryError::OutOfTickets.into());
         }
         for _ in 0..num_tickets {
-            let ticket_id = self.generate_unique_ticket_id();
-            self.ticket_data.insert(ticket_id, user_wallet_address);
+            // The sequence number is the real storage key; the hash below is
+            // only a human-facing receipt code for the purchase log.
+            let seq = self.sold_tickets;
+            let receipt_id = self.generate_unique_ticket_id(clock);
+            self.participants[seq as usize] = user_wallet_address;
+            let (mask, index) = get_mask_and_index_for_seq(seq);
+            self.sold_mask[index] |= mask;
             self.sold_tickets += 1;
+            self.log(LogLevel::INFO, &format!("Ticket #{} sold to {} (receipt {})", seq, user_wallet_address, receipt_id));
         }
         Ok(())
-    }    
-    // Generate Unique Ticket ID
-    fn generate_unique_ticket_id(&self) -> u64 {
+    }
+    // Generate a Ticket Receipt Code, seeded from the Clock sysvar so entropy
+    // derives from on-chain consensus time/slot rather than wall-clock time.
+    fn generate_unique_ticket_id(&self, clock: &Clock) -> u64 {
         let mut hasher = Sha256::new();
-        hasher.update(format!("{}{}", std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs(), self.sold_tickets));
+        hasher.update(format!("{}{}{}{}", self.round, clock.unix_timestamp, clock.slot, self.sold_tickets));
         let result = hasher.finalize();
         let unique_id = u64::from_be_bytes(result[0..8].try_into().unwrap());
         unique_id
     }
-    
-    // Activate Time-Lock
-    pub fn activate_time_lock(&mut self, predefined_duration: u64) -> Result<(), ProgramError> {
+
+    // Byte length of the sold-ticket bitvector for `total_tickets` slots.
+    fn mask_len(total_tickets: u64) -> usize {
+        ((total_tickets + 7) / 8) as usize
+    }
+
+    // Activate Time-Lock: closes sales and moves the lottery into
+    // DrawPending.
+    pub fn activate_time_lock(&mut self, caller: Pubkey, clock: &Clock, predefined_duration: u64) -> Result<(), ProgramError> {
+        self.validate_admin(caller)?;
+        self.assert_phase(Phase::SalesActive)?;
         if self.draw_time != 0 {
-            return Err(ProgramError::Custom(LotteryError::TimeLockAlreadySet as u32));
+            return Err(LotteryError::TimeLockAlreadySet.into());
         }
-        self.draw_time = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs() + predefined_duration;
+        self.draw_time = clock.unix_timestamp as u64 + predefined_duration;
+        self.phase = Phase::DrawPending;
         Ok(())
     }
-    
-    // Execute Chainlink VRF (Pseudo-code, actual implementation needed)
-    pub fn execute_chainlink_vrf(&mut self) -> Result<(), ProgramError> {
-        let current_time = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+
+    // Commit Phase: the admin posts sha256(secret) before sales close, so
+    // the secret itself can't be chosen after the outcome is knowable.
+    pub fn commit_randomness(&mut self, caller: Pubkey, commitment: [u8; 32]) -> Result<(), ProgramError> {
+        self.validate_admin(caller)?;
+        self.assert_phase(Phase::SalesActive)?;
+        if self.draw_time != 0 {
+            return Err(LotteryError::InvalidDrawTime.into());
+        }
+        self.commitment = commitment;
+        Ok(())
+    }
+
+    // Reveal Phase: combine the most recent SlotHashes entry (unknowable at
+    // commit time) with the revealed `secret` (unchangeable after commit)
+    // and the sold-ticket count, closing the manipulation window a bare
+    // VRF stub would leave open.
+    pub fn execute_chainlink_vrf(&mut self, clock: &Clock, slot_hashes: &SlotHashes, secret: &[u8]) -> Result<(), ProgramError> {
+        self.assert_phase(Phase::DrawPending)?;
+        if self.has_outstanding_refunds() {
+            return Err(LotteryError::RefundsOutstanding.into());
+        }
+        let current_time = clock.unix_timestamp as u64;
         if self.draw_time == 0 || current_time < self.draw_time {
-            return Err(ProgramError::Custom(LotteryError::InvalidDrawTime as u32));
+            return Err(LotteryError::InvalidDrawTime.into());
         }
-        // Placeholder for Chainlink VRF
-        self.random_seed = 123456;  // Replace with actual Chainlink VRF call
+
+        let mut commit_hasher = Sha256::new();
+        commit_hasher.update(secret);
+        let commit_check: [u8; 32] = commit_hasher.finalize().into();
+        if commit_check != self.commitment {
+            return Err(LotteryError::InvalidRandomSeed.into());
+        }
+
+        let (_, recent_slot_hash) = slot_hashes
+            .first()
+            .ok_or_else(|| LotteryError::InvalidRandomSeed.into())?;
+
+        let mut hasher = Sha256::new();
+        hasher.update(self.round.to_le_bytes());
+        hasher.update(recent_slot_hash.as_ref());
+        hasher.update(secret);
+        hasher.update(self.sold_tickets.to_le_bytes());
+        let digest = hasher.finalize();
+        self.random_seed = u64::from_be_bytes(digest[0..8].try_into().unwrap());
         Ok(())
     }
     
-    // Sort Tickets
-    fn sort_tickets(&mut self) {
-        let mut ticket_vec: Vec<_> = self.ticket_data.keys().cloned().collect();
-        ticket_vec.sort_by_key(|key| format!("{}{}", key, self.random_seed));
-        
-        // Reconstructing the HashMap
-        let mut sorted_map = HashMap::new();
-        for key in ticket_vec {
-            if let Some(val) = self.ticket_data.get(&key) {  // Note the dereference here
-                sorted_map.insert(key, *val);  // And here
-            }
-        }
-        self.ticket_data = sorted_map;
+    // Successive Sha256(round || seed || i) draws, used as the PRNG stream driving
+    // the Fisher-Yates shuffle below.
+    fn prng_draw(round: u64, seed: u64, i: u64) -> u64 {
+        let mut hasher = Sha256::new();
+        hasher.update(round.to_le_bytes());
+        hasher.update(seed.to_le_bytes());
+        hasher.update(i.to_le_bytes());
+        let digest = hasher.finalize();
+        u64::from_be_bytes(digest[0..8].try_into().unwrap())
+    }
+
+    // Seeded Fisher-Yates shuffle over the sold ticket sequence numbers.
+    // Deterministic and reproducible from `random_seed` alone, unlike
+    // sorting by HashMap iteration order.
+    fn shuffled_sequence(&self) -> Vec<u64> {
+        let mut seqs: Vec<u64> = (0..self.sold_tickets).collect();
+        let mut draw = 0u64;
+        for i in (1..seqs.len()).rev() {
+            let j = (Self::prng_draw(self.round, self.random_seed, draw) % (i as u64 + 1)) as usize;
+            draw += 1;
+            seqs.swap(i, j);
+        }
+        seqs
     }
      // Execute RNG
     pub fn execute_rng(&mut self) -> Result<(), ProgramError> {
+        self.assert_phase(Phase::DrawPending)?;
         if self.random_seed == 0 {
-            return Err(ProgramError::Custom(LotteryError::InvalidRandomSeed as u32));
+            return Err(LotteryError::InvalidRandomSeed.into());
         }
-        self.sort_tickets();
         Ok(())
     }
-    // Select Winners
-    pub fn select_winners(&self) -> Result<(Vec<u64>, u64), ProgramError> {
-        if self.ticket_data.is_empty() {
-            return Err(ProgramError::Custom(LotteryError::NoTicketsSold as u32));
+    // Select Winners: consumes the tier list, handing back the shuffled
+    // winning sequence numbers for each tier in order.
+    pub fn select_winners(&self) -> Result<Vec<Vec<u64>>, ProgramError> {
+        if self.sold_tickets == 0 {
+            return Err(LotteryError::NoTicketsSold.into());
+        }
+        if self.random_seed == 0 {
+            return Err(LotteryError::InvalidRandomSeed.into());
         }
-        // Changed from self.ticket_data.keys().take() to clone and collect
-        let winners = self.ticket_data.keys().cloned().take((self.total_tickets / 10) as usize).collect::<Vec<u64>>();
-        let grand_winner = *self.ticket_data.keys().next().unwrap();
-        Ok((winners, grand_winner))
+        let shuffled = self.shuffled_sequence();
+        let mut offset = 0usize;
+        let mut tiers_winners = Vec::with_capacity(self.payout_structure.tiers.len());
+        for tier in &self.payout_structure.tiers {
+            let end = (offset + tier.winner_count as usize).min(shuffled.len());
+            tiers_winners.push(shuffled[offset..end].to_vec());
+            offset = end;
+        }
+        Ok(tiers_winners)
     }
-    
-    // Calculate Prizes
-    pub fn calculate_prizes(&self) -> (u128, u128) {  // <- Changed to use u128
-        let minor_prize = self.contract_balance * self.payout_structure.minor / 10000;  // <- Changed to use u128
-        let grand_prize = self.contract_balance * self.payout_structure.grand / 10000;  // <- Changed to use u128
-        (minor_prize, grand_prize)
+
+    // Prize Breakdown: per tier, the per-winner lamport amount and the
+    // tier's total allocation, so clients can show an itemized prize table
+    // before and after the draw instead of just two numbers.
+    pub fn prize_breakdown(&self) -> Vec<(u128, u128)> {
+        self.payout_structure.tiers.iter().map(|tier| {
+            let tier_total = self.contract_balance * tier.share_bps / 10000;
+            let per_winner = if tier.winner_count > 0 {
+                tier_total / tier.winner_count as u128
+            } else {
+                0
+            };
+            (per_winner, tier_total)
+        }).collect()
     }
        // Transfer Winnings (Pseudo-code)
-    pub fn transfer_winnings(&mut self, winners: Vec<u64>, grand_winner: u64) -> Result<(), ProgramError> {
-        let (minor_prize, grand_prize) = self.calculate_prizes();
-        
-        // Logic to transfer `minor_prize` to all `winners`
-        // Logic to transfer `grand_prize` to `grand_winner`
-        
+    pub fn transfer_winnings(&mut self, tiers_winners: Vec<Vec<u64>>) -> Result<(), ProgramError> {
+        self.assert_phase(Phase::DrawPending)?;
+        let breakdown = self.prize_breakdown();
+
+        // Logic to transfer, for each tier, `breakdown[i].0` lamports to
+        // each winner in `tiers_winners[i]`
+
+        self.phase = Phase::Complete;
         Ok(())
     }
     // Owner's Fee Collection (Pseudo-code)
     pub fn collect_owner_fee(&mut self) -> Result<(), ProgramError> {
+        self.assert_phase(Phase::Complete)?;
         // Changed from floating point multiplication to integer-based
-        let owner_fee = self.contract_balance * 2 / 10000;  
+        let owner_fee = self.contract_balance * 2 / 10000;
         self.contract_balance -= owner_fee;
-        
+        self.fee_collected = true;
+
         // Logic to transfer `owner_fee` to `self.admin_address`
-        
+
+        Ok(())
+    }
+
+    // Cancel a lottery that never drew, opening the refund window.
+    pub fn cancel_lottery(&mut self, caller: Pubkey) -> Result<(), ProgramError> {
+        self.validate_admin(caller)?;
+        if self.phase != Phase::SalesActive && self.phase != Phase::DrawPending {
+            return Err(LotteryError::InvalidPhase.into());
+        }
+        self.phase = Phase::Refunding;
+        Ok(())
+    }
+
+    // Whether any participant currently has lamports owed back to them:
+    // their full deposit during Refunding, or just the overpayment above
+    // `num_tickets * ticket_price` otherwise. Used to forbid draws while
+    // refunds are outstanding.
+    fn has_outstanding_refunds(&self) -> bool {
+        self.deposits.keys().any(|participant| self.calculate_refund_amount(participant) > 0)
+    }
+
+    // Calculate Refund Amount
+    pub fn calculate_refund_amount(&self, participant: &Pubkey) -> u128 {
+        let deposited = *self.deposits.get(participant).unwrap_or(&0);
+        if self.phase == Phase::Refunding {
+            return deposited;
+        }
+        if self.ticket_price == 0 {
+            return 0;
+        }
+        let num_tickets = self.participants[..self.sold_tickets as usize]
+            .iter()
+            .filter(|holder| **holder == *participant)
+            .count() as u128;
+        let spent = num_tickets * self.ticket_price;
+        deposited.saturating_sub(spent)
+    }
+
+    // Refund: the buyer's full deposit if the lottery was cancelled, or
+    // just their overpayment above `num_tickets * ticket_price` otherwise.
+    pub fn refund(&mut self, participant: Pubkey) -> Result<(), ProgramError> {
+        let amount = self.calculate_refund_amount(&participant);
+        if amount == 0 {
+            return Ok(());
+        }
+        self.contract_balance = self.contract_balance.saturating_sub(amount);
+        if let Some(deposited) = self.deposits.get_mut(&participant) {
+            *deposited -= amount;
+        }
+        // Logic to transfer `amount` lamports back to `participant`
+        Ok(())
+    }
+
+    // Roll over into the next round once a draw is settled: bumps `round`
+    // and resets the per-round state while preserving immutable config, so
+    // the contract can run as a perpetual recurring draw rather than a
+    // single-shot lottery.
+    pub fn roll_over(&mut self, caller: Pubkey) -> Result<(), ProgramError> {
+        self.validate_admin(caller)?;
+        self.assert_phase(Phase::Complete)?;
+        if !self.fee_collected {
+            return Err(LotteryError::FeeNotCollected.into());
+        }
+        self.round += 1;
+        self.participants = vec![Pubkey::default(); self.total_tickets as usize];
+        self.sold_mask = vec![0u8; Self::mask_len(self.total_tickets)];
+        self.sold_tickets = 0;
+        self.contract_balance = 0;
+        self.random_seed = 0;
+        self.draw_time = 0;
+        self.commitment = [0u8; 32];
+        self.deposits.clear();
+        self.fee_collected = false;
+        self.phase = Phase::SalesActive;
         Ok(())
     }
     // Logging
@@ -294,20 +582,32 @@ impl Lottery {
     pub fn log_state_change(&self, state_variable: &str, new_value: &str, changed_by: Pubkey) {
         self.log(LogLevel::INFO, &format!("State change: {} changed to {} by {}", state_variable, new_value, changed_by));
     }
-    // Log Error
+    // Log Error: relies on `LotteryError`'s thiserror `Display` impl, so
+    // the log always carries a human-readable message, not just a variant
+    // name.
     pub fn log_error(&self, error: LotteryError) {
-        self.log(LogLevel::ERROR, &format!("ERROR: {:?}", error));
+        self.log(LogLevel::ERROR, &format!("ERROR: {}", error));
+    }
+
+    // Decode a raw custom error code (e.g. from a CPI return value) back
+    // into a `LotteryError` and print it the way `PrintProgramError` does,
+    // so off-chain tooling doesn't need to maintain its own error table.
+    pub fn log_decoded_error(error_code: u32) {
+        match LotteryError::from_u32(error_code) {
+            Some(error) => error.print::<LotteryError>(),
+            None => msg!("Unknown LotteryError code: {}", error_code),
+        }
     }
     // Error Response
     pub fn error_response(&self, error: LotteryError) -> Result<(), ProgramError> {
-        Err(ProgramError::Custom(error as u32))
+        Err(error.into())
     }
     // Rate Limiting
-    pub fn rate_limit(&mut self, caller: Pubkey) -> Result<(), ProgramError> {
-        let current_time = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+    pub fn rate_limit(&mut self, clock: &Clock, caller: Pubkey) -> Result<(), ProgramError> {
+        let current_time = clock.unix_timestamp as u64;
         let last_time = self.rate_limit_map.entry(caller).or_insert(0);
         if current_time - *last_time < dynamic_rate_limit(caller) {
-            return Err(ProgramError::Custom(LotteryError::RateLimited as u32));
+            return Err(LotteryError::RateLimited.into());
         }
         *last_time = current_time;
         Ok(())
@@ -323,4 +623,139 @@ impl Lottery {
         self.acl.remove(&caller);
         Ok(())
     }
-}      
+}
+
+impl Sealed for Lottery {}
+
+impl IsInitialized for Lottery {
+    fn is_initialized(&self) -> bool {
+        self.total_tickets != 0
+    }
+}
+
+impl Lottery {
+    // Byte size of one packed `PayoutTier` (share_bps: u128, winner_count: u64).
+    pub const TIER_LEN: usize = 16 + 8;
+
+    // Fixed-size prefix of the packed layout, before the variable-length
+    // tier list, participants array, and sold-ticket bitmask.
+    pub const BASE_LEN: usize = 8 // total_tickets
+        + 8 // sold_tickets
+        + 16 // contract_balance
+        + 16 // ticket_price
+        + 8 // draw_time
+        + 8 // random_seed
+        + 32 // admin_address
+        + 32 // commitment
+        + 1 // phase
+        + 8 // round
+        + 1 // fee_collected
+        + 1; // tier_count
+
+    // `Pack::LEN` can't depend on runtime fields, so it reports the fixed
+    // prefix; callers sizing a new account should use `get_packed_len` with
+    // the lottery's `total_tickets` and tier count to get the real size.
+    pub fn get_packed_len(total_tickets: u64, tier_count: usize) -> usize {
+        Self::BASE_LEN + tier_count * Self::TIER_LEN + total_tickets as usize * 32 + Self::mask_len(total_tickets)
+    }
+}
+
+impl Pack for Lottery {
+    const LEN: usize = Self::BASE_LEN;
+
+    fn pack_into_slice(&self, dst: &mut [u8]) {
+        dst[0..8].copy_from_slice(&self.total_tickets.to_le_bytes());
+        dst[8..16].copy_from_slice(&self.sold_tickets.to_le_bytes());
+        dst[16..32].copy_from_slice(&self.contract_balance.to_le_bytes());
+        dst[32..48].copy_from_slice(&self.ticket_price.to_le_bytes());
+        dst[48..56].copy_from_slice(&self.draw_time.to_le_bytes());
+        dst[56..64].copy_from_slice(&self.random_seed.to_le_bytes());
+        dst[64..96].copy_from_slice(self.admin_address.as_ref());
+        dst[96..128].copy_from_slice(&self.commitment);
+        dst[128] = self.phase as u8;
+        dst[129..137].copy_from_slice(&self.round.to_le_bytes());
+        dst[137] = self.fee_collected as u8;
+        dst[138] = self.payout_structure.tiers.len() as u8;
+
+        let mut offset = Self::BASE_LEN;
+        for tier in &self.payout_structure.tiers {
+            dst[offset..offset + 16].copy_from_slice(&tier.share_bps.to_le_bytes());
+            dst[offset + 16..offset + 24].copy_from_slice(&tier.winner_count.to_le_bytes());
+            offset += Self::TIER_LEN;
+        }
+        for participant in &self.participants {
+            dst[offset..offset + 32].copy_from_slice(participant.as_ref());
+            offset += 32;
+        }
+        dst[offset..offset + self.sold_mask.len()].copy_from_slice(&self.sold_mask);
+    }
+
+    fn unpack_from_slice(src: &[u8]) -> Result<Self, ProgramError> {
+        if src.len() < Self::BASE_LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        let total_tickets = u64::from_le_bytes(src[0..8].try_into().unwrap());
+        let sold_tickets = u64::from_le_bytes(src[8..16].try_into().unwrap());
+        let contract_balance = u128::from_le_bytes(src[16..32].try_into().unwrap());
+        let ticket_price = u128::from_le_bytes(src[32..48].try_into().unwrap());
+        let draw_time = u64::from_le_bytes(src[48..56].try_into().unwrap());
+        let random_seed = u64::from_le_bytes(src[56..64].try_into().unwrap());
+        let admin_address = Pubkey::new_from_array(src[64..96].try_into().unwrap());
+        let commitment: [u8; 32] = src[96..128].try_into().unwrap();
+        let phase = match src[128] {
+            0 => Phase::Setup,
+            1 => Phase::SalesActive,
+            2 => Phase::DrawPending,
+            3 => Phase::Complete,
+            4 => Phase::Refunding,
+            _ => return Err(ProgramError::InvalidAccountData),
+        };
+        let round = u64::from_le_bytes(src[129..137].try_into().unwrap());
+        let fee_collected = src[137] != 0;
+        let tier_count = src[138] as usize;
+
+        let mask_len = Self::mask_len(total_tickets);
+        let expected_len = Self::get_packed_len(total_tickets, tier_count);
+        if src.len() < expected_len {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        let mut offset = Self::BASE_LEN;
+        let mut tiers = Vec::with_capacity(tier_count);
+        for _ in 0..tier_count {
+            let share_bps = u128::from_le_bytes(src[offset..offset + 16].try_into().unwrap());
+            let winner_count = u64::from_le_bytes(src[offset + 16..offset + 24].try_into().unwrap());
+            tiers.push(PayoutTier { share_bps, winner_count });
+            offset += Self::TIER_LEN;
+        }
+
+        let mut participants = Vec::with_capacity(total_tickets as usize);
+        for _ in 0..total_tickets {
+            participants.push(Pubkey::new_from_array(src[offset..offset + 32].try_into().unwrap()));
+            offset += 32;
+        }
+        let sold_mask = src[offset..offset + mask_len].to_vec();
+
+        Ok(Lottery {
+            participants,
+            sold_mask,
+            total_tickets,
+            sold_tickets,
+            contract_balance,
+            ticket_price,
+            draw_time,
+            random_seed,
+            commitment,
+            payout_structure: PayoutStructure { tiers },
+            admin_address,
+            // Ephemeral, per-caller scratch state; not part of the account's
+            // durable layout, same as the rate-limit map already was.
+            rate_limit_map: HashMap::new(),
+            acl: HashSet::new(),
+            phase,
+            deposits: HashMap::new(),
+            round,
+            fee_collected,
+        })
+    }
+}